@@ -1,8 +1,23 @@
 use std::sync::Arc;
+use crate::shared::media_thumbnail_cache::{self, MediaThumbnailCacheAction};
 use crate::utils;
+#[cfg(target_os = "linux")]
+use arboard::SetExtLinux;
 
 use makepad_widgets::*;
 
+/// The zoom level at which the image is shown fit-to-viewport (i.e. no zoom at all).
+const FIT_SCALE: f64 = 1.0;
+/// The maximum zoom level a user can reach via scroll/pinch or double-click.
+const MAX_SCALE: f64 = 8.0;
+/// The pixel bound passed to the thumbnail cache when requesting the full-resolution
+/// variant for this viewer; large enough that no realistically-sized source photo gets
+/// downscaled below its native resolution.
+const NATIVE_RESOLUTION_BOUND: usize = 8192;
+/// Below this squared drag distance (in pixels), a `FingerUp` is treated as a tap
+/// (closing the viewer) rather than the end of a pan gesture.
+const TAP_DRAG_THRESHOLD_SQUARED: f64 = 36.0; // 6px
+
 live_design! {
     use link::theme::*;
     use link::shaders::*;
@@ -25,6 +40,33 @@ live_design! {
         <View> {
             align: {x: 1.0, y: 0.0}
             width: Fill, height: Fill
+            spacing: 8
+            save_button = <RobrixIconButton> {
+                padding: {left: 15, right: 15}
+                draw_icon: {
+                    svg_file: (ICON_SAVE)
+                    color: (COLOR_CLOSE),
+                }
+                icon_walk: {width: 18, height: 18, margin: {left: -1, right: -1} }
+
+                draw_bg: {
+                    border_color: (COLOR_CLOSE_BG),
+                    color: (COLOR_CLOSE_BG)
+                }
+            }
+            copy_button = <RobrixIconButton> {
+                padding: {left: 15, right: 15}
+                draw_icon: {
+                    svg_file: (ICON_COPY)
+                    color: (COLOR_CLOSE),
+                }
+                icon_walk: {width: 18, height: 18, margin: {left: -1, right: -1} }
+
+                draw_bg: {
+                    border_color: (COLOR_CLOSE_BG),
+                    color: (COLOR_CLOSE_BG)
+                }
+            }
             close_button = <RobrixIconButton> {
                 padding: {left: 15, right: 15}
                 draw_icon: {
@@ -57,6 +99,42 @@ live_design! {
 pub struct ImageViewer {
     #[deref]
     view: View,
+    /// The `(content_hash, width, height)` of the full-resolution variant currently
+    /// being decoded/resized in the background, if any, used to match up the resulting
+    /// `MediaThumbnailCacheAction`. Matching on the full key (not just the hash) matters
+    /// because the same bytes can be requested at more than one size concurrently (e.g.
+    /// the timeline's thumbnail request and this viewer's native-resolution request
+    /// racing on the same image); keying on the hash alone would let the timeline's
+    /// small thumbnail result clear this viewer's `pending_thumbnail_request` and get
+    /// displayed here instead of the native-resolution variant.
+    #[rust] pending_thumbnail_request: Option<(String, usize, usize)>,
+    /// The size of the currently-displayed image, in pixels, used to compute the "100%"
+    /// zoom level for double-click-to-toggle.
+    #[rust] image_size_in_pixels: Option<(usize, usize)>,
+    /// The current zoom level, where `FIT_SCALE` shows the image fit-to-viewport.
+    #[rust(FIT_SCALE)] scale: f64,
+    /// The current pan translation (in pixels), applied on top of the centered,
+    /// `scale`d image.
+    #[rust] offset: DVec2,
+    /// Whether a finger/mouse button is currently down and dragging the image.
+    #[rust] is_dragging: bool,
+    /// The pointer position at the start of the current press, used to tell a tap
+    /// (click-to-close) apart from the end of a pan.
+    #[rust] drag_start_pos: DVec2,
+    /// The most recent pointer position seen during a drag, used to compute per-move deltas.
+    #[rust] last_drag_pos: DVec2,
+    /// This widget's viewport rect as of the end of the most recent `draw_walk`, used by
+    /// `handle_event` instead of re-reading `Area::rect()` (which reflects the *previous*
+    /// frame's geometry during event handling).
+    #[rust] viewport: Rect,
+    /// The displayed image's rect (not the `image_view` container's, which is
+    /// `width: Fill, height: Fill` and spans the whole viewport) as of the end of the
+    /// most recent `draw_walk`, used to tell a tap that lands on the image apart from
+    /// one that lands on the surrounding blank area (see the `FingerUp` handler below).
+    #[rust] image_rect: Rect,
+    /// The raw, still-encoded bytes of the image currently being displayed, kept resident
+    /// while the modal is open so "Save" and "Copy" can both act on them.
+    #[rust] current_image_bytes: Option<Arc<[u8]>>,
 }
 
 #[derive(Clone, Debug, DefaultNone)]
@@ -69,16 +147,74 @@ pub enum ImageViewerAction {
 
 impl Widget for ImageViewer {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        if self.visible {
+            if let Event::KeyDown(ke) = event {
+                let primary_modifier = ke.modifiers.control || ke.modifiers.logo;
+                if primary_modifier && ke.key_code == KeyCode::KeyS {
+                    self.save_to_disk(cx);
+                } else if primary_modifier && ke.key_code == KeyCode::KeyC {
+                    self.copy_to_clipboard(cx);
+                }
+            }
+        }
+
         let whole_area = self.view.area();
-        let image_area = self.view.image(id!(image_view.image)).area();
-
-        // click the blank area, close image viewer; click image area, nothing happen.
-        event.hits(cx, image_area);
-        if let Hit::FingerUp(fe) = event.hits(cx, whole_area) {
-            if fe.was_tap() {
-                // Once Clicking, we close image viewer.
-                self.close(cx);
+        let viewport = self.viewport;
+
+        match event.hits(cx, whole_area) {
+            Hit::FingerHoverIn(_) | Hit::FingerHoverOver(_) => {
+                cx.set_cursor(if self.is_dragging {
+                    MouseCursor::Grabbing
+                } else if self.scale > FIT_SCALE {
+                    MouseCursor::Grab
+                } else {
+                    MouseCursor::Default
+                });
+            }
+            Hit::FingerDown(fe) => {
+                self.is_dragging = true;
+                self.drag_start_pos = fe.abs;
+                self.last_drag_pos = fe.abs;
+                if self.scale > FIT_SCALE {
+                    cx.set_cursor(MouseCursor::Grabbing);
+                }
             }
+            Hit::FingerMove(fe) => {
+                if self.is_dragging && self.scale > FIT_SCALE {
+                    let delta = fe.abs - self.last_drag_pos;
+                    self.last_drag_pos = fe.abs;
+                    self.offset += delta;
+                    self.clamp_offset(viewport.size);
+                    self.apply_transform(cx);
+                }
+            }
+            Hit::FingerUp(fe) => {
+                self.is_dragging = false;
+                let drag = fe.abs - self.drag_start_pos;
+                let drag_distance_squared = drag.x * drag.x + drag.y * drag.y;
+
+                if fe.tap_count == 2 {
+                    self.toggle_fit_and_full(cx, fe.abs, viewport);
+                } else if self.scale <= FIT_SCALE
+                    && fe.was_tap()
+                    && drag_distance_squared <= TAP_DRAG_THRESHOLD_SQUARED
+                    && !self.image_rect.contains(fe.abs)
+                {
+                    // A tap on the blank area around the fit-scale image closes the
+                    // viewer. A tap on the image itself is left alone instead, since
+                    // closing on its first `FingerUp` (which always carries
+                    // `tap_count == 1`) would consume the gesture before a following
+                    // second tap could ever be recognized as `tap_count == 2`, making
+                    // double-click-to-zoom unreachable from the default fit state.
+                    self.close(cx);
+                } else {
+                    cx.set_cursor(if self.scale > FIT_SCALE { MouseCursor::Grab } else { MouseCursor::Default });
+                }
+            }
+            Hit::FingerScroll(fe) => {
+                self.zoom_at(cx, fe.abs, -fe.scroll.y, viewport);
+            }
+            _ => {}
         }
 
         self.match_event(cx, event);
@@ -86,7 +222,9 @@ impl Widget for ImageViewer {
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
-        self.view.draw_walk(cx, scope, walk)
+        let draw_step = self.view.draw_walk(cx, scope, walk);
+        self.after_layout(cx);
+        draw_step
     }
 }
 impl MatchEvent for ImageViewer {
@@ -95,37 +233,242 @@ impl MatchEvent for ImageViewer {
             // Clear the image cache once the modal is closed.
             self.close(cx);
         }
+        if self.view.button(id!(save_button)).clicked(actions) {
+            self.save_to_disk(cx);
+        }
+        if self.view.button(id!(copy_button)).clicked(actions) {
+            self.copy_to_clipboard(cx);
+        }
 
         for action in actions {
             if let Some(ImageViewerAction::Show(data)) = action.downcast_ref() {
                 self.view.image(id!(image_view.image)).set_texture(cx, None);
+                self.current_image_bytes = Some(data.clone());
                 self.open(cx);
                 self.load_with_data(cx, data);
             }
+            match action.downcast_ref() {
+                Some(MediaThumbnailCacheAction::Loaded { content_hash, width, height, resized_png_bytes })
+                    if self.pending_thumbnail_request.as_ref()
+                        == Some(&(content_hash.clone(), *width, *height)) =>
+                {
+                    self.pending_thumbnail_request = None;
+                    let bytes = resized_png_bytes.clone();
+                    self.apply_decoded_image(cx, &bytes);
+                }
+                Some(MediaThumbnailCacheAction::Failed { content_hash, width, height, error })
+                    if self.pending_thumbnail_request.as_ref()
+                        == Some(&(content_hash.clone(), *width, *height)) =>
+                {
+                    self.pending_thumbnail_request = None;
+                    log!("Error to load image: {error}");
+                }
+                _ => {}
+            }
         }
     }
 }
 
 impl ImageViewer {
+    /// Caches this frame's just-finished layout for `handle_event` to read on the next
+    /// event cycle, so zoom/pan math and the click-to-close hit region are derived from
+    /// current-frame geometry rather than whatever the previous frame happened to leave
+    /// behind (which made hit results depend on overlay draw ordering).
+    fn after_layout(&mut self, cx: &mut Cx2d) {
+        self.viewport = self.view.area().rect(cx);
+        self.image_rect = self.view.image(id!(image_view.image)).area().rect(cx);
+    }
+
     fn open(&mut self, cx: &mut Cx) {
         self.visible = true;
+        cx.set_key_focus(self.view.area());
         self.redraw(cx);
     }
     fn close(&mut self, cx: &mut Cx) {
         self.visible = false;
         self.clear_texture(cx);
+        self.reset_zoom_and_pan(cx);
+        self.current_image_bytes = None;
         self.redraw(cx);
     }
+
+    /// Resets the zoom/pan state back to fit-to-viewport, e.g. when closing the viewer
+    /// or loading a new image.
+    fn reset_zoom_and_pan(&mut self, cx: &mut Cx) {
+        self.scale = FIT_SCALE;
+        self.offset = DVec2::default();
+        self.view.view(id!(image_view)).apply_over(cx, live! {
+            width: Fill, height: Fill,
+            margin: { left: 0, top: 0 },
+        });
+    }
     fn clear_texture(&mut self, cx: &mut Cx) {
         self.view.image(id!(image_view.image)).set_texture(cx, None);
     }
-    fn load_with_data(&mut self, cx: &mut Cx, data: &[u8]) {
+    /// Kicks off a background decode + resize of `data` to its native resolution, so
+    /// the timeline's small thumbnail texture is never reused at full size and zooming
+    /// in (up to `MAX_SCALE`) has real detail to show.
+    ///
+    /// `NATIVE_RESOLUTION_BOUND` is only an upper bound passed to the thumbnail cache,
+    /// which never upscales past the source image's actual resolution; it's large
+    /// enough that any realistically-sized photo decodes at its native size.
+    fn load_with_data(&mut self, cx: &mut Cx, data: &Arc<[u8]>) {
+        self.reset_zoom_and_pan(cx);
+        let content_hash = media_thumbnail_cache::content_hash(data);
+        self.pending_thumbnail_request = Some((content_hash, NATIVE_RESOLUTION_BOUND, NATIVE_RESOLUTION_BOUND));
+        media_thumbnail_cache::request_thumbnail(
+            data.clone(),
+            NATIVE_RESOLUTION_BOUND,
+            NATIVE_RESOLUTION_BOUND,
+        );
+    }
+
+    fn apply_decoded_image(&mut self, cx: &mut Cx, data: &[u8]) {
         let image = self.view.image(id!(image_view.image));
 
-        if let Err(e) = utils::load_png_or_jpg(&image, cx, data) {
-            log!("Error to load image: {e}");
-        } else {
-            self.view.redraw(cx);
+        match utils::load_png_or_jpg(&image, cx, data) {
+            Ok(size_in_pixels) => {
+                self.image_size_in_pixels = Some(size_in_pixels);
+                self.view.redraw(cx);
+            }
+            Err(e) => log!("Error to load image: {e}"),
         }
     }
+
+    /// Clamps `self.offset` so that the scaled image's edges can never be dragged past
+    /// the viewport's center, given the viewport's current `size`.
+    fn clamp_offset(&mut self, viewport_size: DVec2) {
+        let max_offset = ((self.scale - 1.0) * viewport_size * 0.5).max(DVec2::default());
+        self.offset.x = self.offset.x.clamp(-max_offset.x, max_offset.x);
+        self.offset.y = self.offset.y.clamp(-max_offset.y, max_offset.y);
+    }
+
+    /// Zooms in/out by `wheel_delta` (positive = zoom in) centered on `cursor`, keeping
+    /// the image point under the cursor fixed in place.
+    fn zoom_at(&mut self, cx: &mut Cx, cursor: DVec2, wheel_delta: f64, viewport: Rect) {
+        let old_scale = self.scale;
+        let new_scale = (old_scale * (1.0 + wheel_delta * 0.1)).clamp(FIT_SCALE, MAX_SCALE);
+        if new_scale == old_scale {
+            return;
+        }
+
+        let center = viewport.pos + viewport.size * 0.5;
+        let cursor_from_center = cursor - center;
+        self.offset = cursor_from_center - (cursor_from_center - self.offset) * (new_scale / old_scale);
+        self.scale = new_scale;
+        self.clamp_offset(viewport.size);
+        self.apply_transform(cx);
+    }
+
+    /// Toggles between fit-to-viewport and the image's "100%" (native pixel) zoom level,
+    /// matching the common double-click/double-tap image-viewer convention.
+    fn toggle_fit_and_full(&mut self, cx: &mut Cx, cursor: DVec2, viewport: Rect) {
+        if self.scale > FIT_SCALE {
+            self.scale = FIT_SCALE;
+            self.offset = DVec2::default();
+            self.apply_transform(cx);
+            return;
+        }
+
+        let full_scale = self.image_size_in_pixels
+            .map(|(width, height)| {
+                f64::max(width as f64 / viewport.size.x, height as f64 / viewport.size.y)
+            })
+            .unwrap_or(2.0)
+            .clamp(FIT_SCALE, MAX_SCALE);
+
+        self.zoom_at(cx, cursor, (full_scale / FIT_SCALE - 1.0) / 0.1, viewport);
+    }
+
+    /// Opens a native "save file" dialog and writes the currently-displayed image to
+    /// disk, choosing a `.png`/`.jpg` extension from the decoded format: JPEG bytes are
+    /// written as-is, and any other format is re-encoded to PNG via the `image` crate.
+    ///
+    /// The dialog itself is shown synchronously on the calling (UI) thread: rfd's
+    /// blocking dialogs must run on the main thread (macOS panics/hangs otherwise), so
+    /// only the encode-and-write work, which doesn't touch any UI toolkit state, is
+    /// handed off to a background thread.
+    fn save_to_disk(&mut self, _cx: &mut Cx) {
+        let Some(data) = self.current_image_bytes.clone() else { return };
+        let is_jpeg = matches!(image::guess_format(&data), Ok(image::ImageFormat::Jpeg));
+        let extension = if is_jpeg { "jpg" } else { "png" };
+
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(format!("image.{extension}"))
+            .add_filter("Image", &[extension])
+            .save_file()
+        else { return };
+
+        std::thread::spawn(move || {
+            let result = if is_jpeg {
+                std::fs::write(&path, &*data)
+            } else {
+                match image::load_from_memory(&data) {
+                    Ok(img) => img.save_with_format(&path, image::ImageFormat::Png)
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
+                    Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
+                }
+            };
+            if let Err(e) = result {
+                log!("Error saving image to disk: {e}");
+            }
+        });
+    }
+
+    /// Decodes the currently-displayed image to RGBA and places it onto the system
+    /// clipboard.
+    fn copy_to_clipboard(&mut self, _cx: &mut Cx) {
+        let Some(data) = self.current_image_bytes.clone() else { return };
+        std::thread::spawn(move || {
+            let rgba = match image::load_from_memory(&data) {
+                Ok(img) => img.to_rgba8(),
+                Err(e) => {
+                    log!("Error decoding image for clipboard: {e}");
+                    return;
+                }
+            };
+            let (width, height) = rgba.dimensions();
+            let image_data = arboard::ImageData {
+                width: width as usize,
+                height: height as usize,
+                bytes: rgba.into_raw().into(),
+            };
+            let mut clipboard = match arboard::Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    log!("Error copying image to clipboard: {e}");
+                    return;
+                }
+            };
+            // On Linux (X11/Wayland), the clipboard offer is served by a thread tied to
+            // the `Clipboard`'s lifetime, so a bare `set_image` followed by an immediate
+            // drop loses the contents as soon as this thread exits. `.set().wait()`
+            // blocks this (background, not the UI) thread, keeping the clipboard alive
+            // until another application takes ownership of it.
+            #[cfg(target_os = "linux")]
+            let result = clipboard.set().wait().image(image_data);
+            #[cfg(not(target_os = "linux"))]
+            let result = clipboard.set_image(image_data);
+            if let Err(e) = result {
+                log!("Error copying image to clipboard: {e}");
+            }
+        });
+    }
+
+    /// Applies `self.scale`/`self.offset` to the image's container by resizing it
+    /// relative to the viewport and centering it with the user's pan offset, so the
+    /// inner image (which keeps `fit: Smallest`) scales up proportionally.
+    fn apply_transform(&mut self, cx: &mut Cx) {
+        let viewport = self.viewport.size;
+        let width = viewport.x * self.scale;
+        let height = viewport.y * self.scale;
+        let left = (viewport.x - width) * 0.5 + self.offset.x;
+        let top = (viewport.y - height) * 0.5 + self.offset.y;
+
+        self.view.view(id!(image_view)).apply_over(cx, live! {
+            width: (width), height: (height),
+            margin: { left: (left), top: (top) },
+        });
+        self.view.redraw(cx);
+    }
 }
@@ -32,39 +32,52 @@ struct RobrixAutoFitImage {
 
 impl Widget for RobrixAutoFitImage {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+    }
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let draw_step = self.view.draw_walk(cx, scope, walk);
+        self.after_layout(cx);
+        draw_step
+    }
+}
+
+impl RobrixAutoFitImage {
+    /// Recomputes the fit-mode (`Size`/`Smallest`) threshold and `target_size` from
+    /// this frame's just-finished layout.
+    ///
+    /// This must run right after `draw_walk` lays out the image, rather than in
+    /// `handle_event`: reading `Area::rect()` during event handling reflects the
+    /// *previous* frame's geometry, which caused a one-frame lag (and visible flicker)
+    /// when the window was resized, and latched the first-frame `target_size` from
+    /// stale area data.
+    fn after_layout(&mut self, cx: &mut Cx2d) {
         let image = self.view.image(id!(image));
         if !image.has_texture() { return }
 
-        if let Some(target_size) = self.target_size {
-            if let Event::Actions(_) | Event::WindowGeomChange(_) = event {
-                let current_size = self.view.area().rect(cx).size;
-                let new_status = if current_size.x > target_size.x { ImageStatus::Size } else { ImageStatus::Smallest };
-                if self.status != new_status {
-                    match new_status {
-                        ImageStatus::Size => {
-                            image.apply_over(cx, live! {
-                                width: Fill, height: Fill
-                                fit: Size
-                            });
-                        },
-                        ImageStatus::Smallest => {
-                            image.apply_over(cx, live! {
-                                width: Fill, height: Fit
-                                fit: Smallest
-                            });
-                        }
-                    }
-                    self.status = new_status;
-                }
-            }
-        } else {
+        if self.target_size.is_none() {
             self.target_size = Some(image.area().rect(cx).size);
         }
+        let Some(target_size) = self.target_size else { return };
 
-        self.view.handle_event(cx, event, scope);
-    }
-    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
-        self.view.draw_walk(cx, scope, walk)
+        let current_size = self.view.area().rect(cx).size;
+        let new_status = if current_size.x > target_size.x { ImageStatus::Size } else { ImageStatus::Smallest };
+        if self.status != new_status {
+            match new_status {
+                ImageStatus::Size => {
+                    image.apply_over(cx, live! {
+                        width: Fill, height: Fill
+                        fit: Size
+                    });
+                },
+                ImageStatus::Smallest => {
+                    image.apply_over(cx, live! {
+                        width: Fill, height: Fit
+                        fit: Smallest
+                    });
+                }
+            }
+            self.status = new_status;
+        }
     }
 }
 
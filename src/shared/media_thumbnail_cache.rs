@@ -0,0 +1,182 @@
+//! A content-addressed, on-disk cache of decoded images and their resized thumbnails.
+//!
+//! Decoding and resizing a full-resolution image is expensive, so we only want to do it
+//! once per image and once per `(image bytes, target size)` pair. This module hashes the
+//! raw image bytes to derive a stable cache key, decodes the original once (reusing
+//! that decode across every later target size requested for the same bytes), and
+//! persists both the decoded original and each resized variant to disk under the OS
+//! cache directory, so that later runs (and other widgets asking for the same image)
+//! can skip decoding entirely.
+//!
+//! All cache work happens on a background thread; callers get the result back as a
+//! [`MediaThumbnailCacheAction`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use makepad_widgets::*;
+use sha2::{Digest, Sha256};
+
+/// The subdirectory (within the OS cache directory) that Robrix stores thumbnails in.
+const THUMBNAIL_CACHE_SUBDIR: &str = "robrix/thumbnails";
+
+/// An action emitted once a requested thumbnail is ready (or has failed to produce).
+#[derive(Clone, Debug, DefaultNone)]
+pub enum MediaThumbnailCacheAction {
+    /// The thumbnail for `content_hash` at `(width, height)` is ready to be displayed.
+    Loaded {
+        content_hash: String,
+        width: usize,
+        height: usize,
+        resized_png_bytes: Arc<[u8]>,
+    },
+    /// Decoding or resizing the original image data at `(width, height)` failed.
+    Failed { content_hash: String, width: usize, height: usize, error: String },
+    None,
+}
+
+/// Computes the stable content key used to name cache files on disk: a URL-safe
+/// base64 encoding of the SHA-256 digest of `bytes`.
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+/// The `(content_hash, width, height)` requests currently being decoded/resized on a
+/// background thread, used to coalesce duplicate requests (e.g. from rapid timeline
+/// scrolling or a resize re-triggering the same size repeatedly).
+fn in_flight_requests() -> &'static Mutex<HashSet<(String, usize, usize)>> {
+    static IN_FLIGHT: OnceLock<Mutex<HashSet<(String, usize, usize)>>> = OnceLock::new();
+    IN_FLIGHT.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// In-memory cache of already-decoded originals, keyed by content hash, so that
+/// multiple target sizes for the same image (different widget sizes, a window
+/// resize, or the same image shown in both `TextOrImage` and `ImageViewer`) only pay
+/// the decode cost once per process run.
+fn decoded_originals_cache() -> &'static Mutex<HashMap<String, Arc<DynamicImage>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Arc<DynamicImage>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Requests a thumbnail of `original_bytes` resized to fit within `(width, height)`.
+///
+/// This never blocks the calling (UI) thread: the cache lookup, and the decode/resize
+/// on a miss, both happen on a background thread. The result is delivered later as a
+/// [`MediaThumbnailCacheAction`] posted back to the UI. If an identical
+/// `(content hash, width, height)` request is already in flight, this call is a no-op:
+/// the in-flight request's result is broadcast to every caller waiting on it.
+pub fn request_thumbnail(original_bytes: Arc<[u8]>, width: usize, height: usize) {
+    let content_hash = content_hash(&original_bytes);
+    let key = (content_hash.clone(), width, height);
+    if !in_flight_requests().lock().unwrap().insert(key.clone()) {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let result = load_or_create_thumbnail(&content_hash, &original_bytes, width, height);
+        in_flight_requests().lock().unwrap().remove(&key);
+        match result {
+            Ok(resized_png_bytes) => Cx::post_action(MediaThumbnailCacheAction::Loaded {
+                content_hash,
+                width,
+                height,
+                resized_png_bytes,
+            }),
+            Err(error) => Cx::post_action(MediaThumbnailCacheAction::Failed {
+                content_hash,
+                width,
+                height,
+                error: error.to_string(),
+            }),
+        }
+    });
+}
+
+fn thumbnail_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(THUMBNAIL_CACHE_SUBDIR)
+}
+
+fn thumbnail_path(content_hash: &str, width: usize, height: usize) -> PathBuf {
+    thumbnail_cache_dir().join(format!("{content_hash}_{width}x{height}.png"))
+}
+
+fn original_path(content_hash: &str) -> PathBuf {
+    thumbnail_cache_dir().join(format!("{content_hash}_original.png"))
+}
+
+/// Returns the decoded original for `content_hash`: first from the in-memory cache,
+/// then from the on-disk decoded-original cache, and only falling back to a real decode
+/// (which is then persisted both in memory and to disk) on a full miss.
+///
+/// The in-memory cache lock is not held while decoding, so concurrent requests for the
+/// same bytes that both miss (e.g. two different target sizes) each decode them.
+fn decode_original(content_hash: &str, original_bytes: &[u8]) -> anyhow::Result<Arc<DynamicImage>> {
+    if let Some(decoded) = decoded_originals_cache().lock().unwrap().get(content_hash) {
+        return Ok(decoded.clone());
+    }
+
+    let path = original_path(content_hash);
+    let decoded = if let Ok(cached_bytes) = std::fs::read(&path) {
+        image::load_from_memory(&cached_bytes)?
+    } else {
+        let decoded = image::load_from_memory(original_bytes)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        decoded.save_with_format(&path, ImageFormat::Png)?;
+        decoded
+    };
+
+    let decoded = Arc::new(decoded);
+    decoded_originals_cache().lock().unwrap().insert(content_hash.to_string(), decoded.clone());
+    Ok(decoded)
+}
+
+/// Loads the resized thumbnail from disk if it's already cached; otherwise reuses (or
+/// creates) the decoded original via [`decode_original`], downscales it to fit within
+/// `(width, height)` without ever upscaling past the source resolution, writes the
+/// result to disk, and returns it.
+fn load_or_create_thumbnail(
+    content_hash: &str,
+    original_bytes: &[u8],
+    width: usize,
+    height: usize,
+) -> anyhow::Result<Arc<[u8]>> {
+    let path = thumbnail_path(content_hash, width, height);
+    if let Ok(existing) = std::fs::read(&path) {
+        return Ok(Arc::from(existing));
+    }
+
+    let original = decode_original(content_hash, original_bytes)?;
+    let (src_width, src_height) = (original.width(), original.height());
+    // Fit within `(width, height)` while preserving aspect ratio, and never upscale.
+    let scale = f64::min(
+        width as f64 / src_width as f64,
+        height as f64 / src_height as f64,
+    )
+    .min(1.0);
+    let target_width = ((src_width as f64 * scale).round() as u32).max(1);
+    let target_height = ((src_height as f64 * scale).round() as u32).max(1);
+    let resized = image::imageops::resize(&*original, target_width, target_height, FilterType::Lanczos3);
+
+    let mut resized_bytes = Cursor::new(Vec::new());
+    resized.write_to(&mut resized_bytes, ImageFormat::Png)?;
+    let resized_bytes = resized_bytes.into_inner();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, &resized_bytes)?;
+
+    Ok(Arc::from(resized_bytes))
+}
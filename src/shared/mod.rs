@@ -5,6 +5,7 @@ pub mod avatar;
 pub mod clickable_view;
 pub mod helpers;
 pub mod html_or_plaintext;
+pub mod media_thumbnail_cache;
 pub mod search_bar;
 pub mod styles;
 pub mod text_or_image;
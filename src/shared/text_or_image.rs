@@ -1,12 +1,28 @@
-//! A `TextOrImage` view displays either a text label or an image.
+//! A `TextOrImage` view displays either a loading spinner, an image, or a retryable
+//! error message.
 //!
-//! This is useful to display a loading message while waiting for an image to be fetched,
-//! or to display an error message if the image fails to load, etc.
+//! This is useful to show an animated loading placeholder while waiting for an image
+//! to be fetched, and to let the user tap to retry if that fetch failed.
 
 use std::sync::Arc;
 use makepad_widgets::*;
 use matrix_sdk::ruma::OwnedMxcUri;
 
+use crate::shared::media_thumbnail_cache::{self, MediaThumbnailCacheAction};
+use crate::utils;
+
+/// How far (in radians) the spinner rotates per `NextFrame` tick.
+const SPINNER_RADIANS_PER_FRAME: f32 = 0.2;
+/// The height bound passed to the thumbnail cache when requesting a timeline
+/// thumbnail. `image_view`'s `image` is `width: Fill, height: Fit`, so the widget's
+/// *displayed* height is derived from the image's aspect ratio at `width: Fill`, not
+/// fixed ahead of time; passing a generous height bound (rather than this widget's
+/// current layout height, which is still the `loading_view` placeholder's `Fit` height
+/// around a 16px spinner at request time) keeps the widget's layout width as the only
+/// real constraint on the resize, so the decoded thumbnail isn't undersized for the
+/// image's eventual aspect-correct display height.
+const THUMBNAIL_MAX_HEIGHT: usize = 4096;
+
 live_design! {
     use link::theme::*;
     use link::shaders::*;
@@ -14,17 +30,64 @@ live_design! {
 
     use crate::shared::styles::*;
 
+    // A small rotating arc, driven by the `rotation` uniform, used as the loading spinner.
+    Spinner = <View> {
+        width: 16, height: 16,
+        spinner_icon = <View> {
+            width: Fill, height: Fill,
+            show_bg: true,
+            draw_bg: {
+                uniform rotation: 0.0
+                fn pixel(self) -> vec4 {
+                    let center = vec2(0.5, 0.5)
+                    let d = self.pos - center
+                    let radius = 0.5
+                    let thickness = 0.08
+                    let dist = length(d) - (radius - thickness * 0.5)
+                    let ring = 1.0 - smoothstep(0.0, thickness * 0.5, abs(dist))
+
+                    let angle = atan(d.y, d.x) - self.rotation
+                    let tau = 6.28318530718
+                    let wrapped = mod(angle, tau)
+                    let arc = smoothstep(0.0, tau * 0.75, wrapped) * (1.0 - smoothstep(tau * 0.75, tau, wrapped))
+
+                    return vec4(0.4, 0.4, 0.4, 1.0) * (ring * arc)
+                }
+            }
+        }
+    }
+
     pub TextOrImage = {{TextOrImage}} {
         width: Fill, height: Fit,
         flow: Overlay,
 
-        text_view = <View> {
+        loading_view = <View> {
             visible: true,
             show_bg: true,
             draw_bg: {
                 color: #dddddd
             }
             width: Fill, height: Fit,
+            align: {x: 0.0, y: 0.5},
+            spacing: 8,
+            spinner = <Spinner> { }
+            label = <Label> {
+                width: Fill, height: Fit,
+                draw_text: {
+                    wrap: Word,
+                    text_style: <MESSAGE_TEXT_STYLE> { }
+                    color: (MESSAGE_TEXT_COLOR),
+                }
+            }
+        }
+        failed_view = <View> {
+            visible: false,
+            cursor: Hand,
+            show_bg: true,
+            draw_bg: {
+                color: #dddddd
+            }
+            width: Fill, height: Fit,
             label = <Label> {
                 width: Fill, height: Fit,
                 draw_text: {
@@ -49,6 +112,9 @@ live_design! {
 #[derive(Debug, Clone, DefaultNone)]
 pub enum TextOrImageAction {
     Click(OwnedMxcUri),
+    /// Emitted when the user taps a failed `TextOrImage`, requesting that the enclosing
+    /// timeline re-kick the fetch for the given mxc URI.
+    Retry(OwnedMxcUri),
     None,
 }
 
@@ -58,60 +124,158 @@ pub struct ImageValue {
     pub timeline_image_data: Arc<[u8]>,
 }
 
-/// A view that holds an image or text content, and can switch between the two.
+/// A view that holds a loading spinner, an image, or a retryable error message, and
+/// can switch between the three.
 ///
-/// This is useful for displaying alternate text when an image is not (y680-121-263et) available
-/// or fails to load. It can also be used to display a loading message while an image
-/// is being fetched.
+/// This is useful for displaying a loading message while an image is being fetched,
+/// and for letting the user retry a transiently-failed fetch by tapping the error
+/// message, rather than leaving a dead placeholder.
 #[derive(Live, Widget, LiveHook)]
 pub struct TextOrImage {
     #[deref] view: View,
     #[rust] status: TextOrImageStatus,
     #[rust] size_in_pixels: (usize, usize),
     #[rust] image_value: Option<ImageValue>,
+    /// The `(content_hash, width, height)` of the thumbnail currently being
+    /// decoded/resized in the background, if any, used to match up the resulting
+    /// `MediaThumbnailCacheAction`. Matching on the full key (not just the hash) matters
+    /// because the same image can be requested at more than one size concurrently (e.g.
+    /// a timeline thumbnail and the viewer's native-resolution request racing on the
+    /// same bytes); keying on the hash alone would let either request's result clear the
+    /// other's pending state.
+    #[rust] pending_thumbnail_request: Option<(String, usize, usize)>,
+    /// The current rotation (in radians) of the loading spinner icon.
+    #[rust] spinner_rotation: f32,
+    /// The pending `NextFrame` event that advances the spinner, if the spinner is currently
+    /// animating.
+    #[rust] spinner_next_frame: Option<NextFrame>,
 }
 
 impl Widget for TextOrImage {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
-        // We handle hit events if the status is `Image`.
-        if let TextOrImageStatus::Image = self.status {
-            let image_area = self.view.image(id!(image_view.image)).area();
-            match event.hits(cx, image_area) {
-                Hit::FingerDown(_) => {
-                    cx.set_key_focus(image_area);
+        match &self.status {
+            TextOrImageStatus::Image => {
+                let image_area = self.view.image(id!(image_view.image)).area();
+                match event.hits(cx, image_area) {
+                    Hit::FingerDown(_) => {
+                        cx.set_key_focus(image_area);
+                    }
+                    Hit::FingerUp(fe) if fe.is_over && fe.is_primary_hit() && fe.was_tap() => {
+                        // We run the check to see if the original image was already fetched or not.
+                        //
+                        // If `image_value` is `None`, it can tell that the image has not been fetched,
+                        // user actually clicks the blurhash,
+                        // so we do nothing this condition.
+                        if let Some(image_value) = self.image_value.as_ref() {
+                            cx.action(TextOrImageAction::Click(image_value.original_mxc_uri.clone()));
+                        }
+                    }
+                    _ => { },
                 }
-                Hit::FingerUp(fe) if fe.is_over && fe.is_primary_hit() && fe.was_tap() => {
-                    // We run the check to see if the original image was already fetched or not.
-                    //
-                    // If `image_value` is `None`, it can tell that the image has not been fetched,
-                    // user actually clicks the blurhash,
-                    // so we do nothing this condition.
-                    if let Some(image_value) = self.image_value.as_ref() {
-                        cx.action(TextOrImageAction::Click(image_value.original_mxc_uri.clone()));
+            }
+            TextOrImageStatus::Failed(_) => {
+                let failed_area = self.view(id!(failed_view)).area();
+                match event.hits(cx, failed_area) {
+                    Hit::FingerDown(_) => {
+                        cx.set_key_focus(failed_area);
+                    }
+                    Hit::FingerUp(fe) if fe.is_over && fe.is_primary_hit() && fe.was_tap() => {
+                        if let Some(image_value) = self.image_value.as_ref() {
+                            cx.action(TextOrImageAction::Retry(image_value.original_mxc_uri.clone()));
+                        }
                     }
+                    _ => { },
                 }
-                _ => { },
             }
+            TextOrImageStatus::Loading => { }
         }
+
+        if let Event::NextFrame(ne) = event {
+            if self.spinner_next_frame.is_some_and(|nf| ne.set.contains(&nf)) {
+                if matches!(self.status, TextOrImageStatus::Loading) {
+                    self.spinner_rotation += SPINNER_RADIANS_PER_FRAME;
+                    self.update_spinner(cx);
+                    self.spinner_next_frame = Some(cx.new_next_frame());
+                } else {
+                    self.spinner_next_frame = None;
+                }
+            }
+        }
+
+        self.match_event(cx, event);
         self.view.handle_event(cx, event, scope);
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        if matches!(self.status, TextOrImageStatus::Loading) && self.spinner_next_frame.is_none() {
+            self.spinner_next_frame = Some(cx.new_next_frame());
+        }
         self.view.draw_walk(cx, scope, walk)
     }
 }
 
+impl MatchEvent for TextOrImage {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions) {
+        let Some(pending_request) = self.pending_thumbnail_request.clone() else { return };
+        for action in actions {
+            match action.downcast_ref() {
+                Some(MediaThumbnailCacheAction::Loaded { content_hash, width, height, resized_png_bytes })
+                    if (content_hash.as_str(), *width, *height) == (pending_request.0.as_str(), pending_request.1, pending_request.2) =>
+                {
+                    self.pending_thumbnail_request = None;
+                    let bytes = resized_png_bytes.clone();
+                    let _ = self.show_image(cx, |cx, image_ref| {
+                        utils::load_png_or_jpg(&image_ref, cx, &bytes)
+                    });
+                }
+                Some(MediaThumbnailCacheAction::Failed { content_hash, width, height, error })
+                    if (content_hash.as_str(), *width, *height) == (pending_request.0.as_str(), pending_request.1, pending_request.2) =>
+                {
+                    self.pending_thumbnail_request = None;
+                    self.show_failed(cx, error.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 impl TextOrImage {
-    /// Sets the text content, which will be displayed on future draw operations.
+    /// Sets the loading message, which is displayed alongside an animated, rotating
+    /// spinner icon on future draw operations.
     ///
     /// ## Arguments
-    /// * `text`: the text that will be displayed in this `TextOrImage`, e.g.,
-    ///   a message like "Loading..." or an error message.
+    /// * `text`: the message that will be displayed next to the spinner, e.g.,
+    ///   "Loading image...".
     fn show_text<T: AsRef<str>>(&mut self, cx: &mut Cx, text: T) {
         self.view(id!(image_view)).set_visible(cx, false);
-        self.view(id!(text_view)).set_visible(cx, true);
-        self.view.label(id!(text_view.label)).set_text(cx, text.as_ref());
-        self.status = TextOrImageStatus::Text;
+        self.view(id!(failed_view)).set_visible(cx, false);
+        self.view(id!(loading_view)).set_visible(cx, true);
+        self.view.label(id!(loading_view.label)).set_text(cx, text.as_ref());
+        self.status = TextOrImageStatus::Loading;
+        self.update_spinner(cx);
+    }
+
+    /// Displays `reason` as an error message with a tap-to-retry affordance.
+    ///
+    /// Tapping the failed view emits a [`TextOrImageAction::Retry`] carrying the
+    /// original mxc URI (if one was previously associated via
+    /// [`Self::show_image_from_data`] or
+    /// [`TextOrImageRef::set_original_mxc_uri_and_timeline_image_data`]), so the
+    /// enclosing timeline can re-kick the fetch rather than leaving a dead placeholder.
+    pub fn show_failed<T: AsRef<str>>(&mut self, cx: &mut Cx, reason: T) {
+        self.view(id!(image_view)).set_visible(cx, false);
+        self.view(id!(loading_view)).set_visible(cx, false);
+        self.view(id!(failed_view)).set_visible(cx, true);
+        self.view.label(id!(failed_view.label)).set_text(cx, reason.as_ref());
+        self.status = TextOrImageStatus::Failed(reason.as_ref().to_string());
+    }
+
+    /// Applies the current `spinner_rotation` to the loading spinner's rotation uniform.
+    fn update_spinner(&mut self, cx: &mut Cx) {
+        self.view(id!(loading_view.spinner.spinner_icon)).apply_over(cx, live! {
+            draw_bg: { rotation: (self.spinner_rotation) }
+        });
     }
 
     /// Sets the image content, which will be displayed on future draw operations.
@@ -123,7 +287,7 @@ impl TextOrImage {
     ///   * If successful, the `image_set_function` should return the size of the image
     ///     in pixels as a tuple, `(width, height)`.
     ///   * If `image_set_function` returns an error, no change is made to this `TextOrImage`.
-    pub fn show_image<F, E>(&mut self, cx: &mut Cx, image_set_function: F) -> Result<(), E>
+    pub fn show_image<F, E: ToString>(&mut self, cx: &mut Cx, image_set_function: F) -> Result<(), E>
         where F: FnOnce(&mut Cx, ImageRef) -> Result<(usize, usize), E>
     {
         let image_ref = self.view.image(id!(image_view.image));
@@ -132,19 +296,38 @@ impl TextOrImage {
                 self.status = TextOrImageStatus::Image;
                 self.size_in_pixels = size_in_pixels;
                 self.view(id!(image_view)).set_visible(cx, true);
-                self.view(id!(text_view)).set_visible(cx, false);
+                self.view(id!(loading_view)).set_visible(cx, false);
+                self.view(id!(failed_view)).set_visible(cx, false);
                 Ok(())
             }
             Err(e) => {
-                self.show_text(cx, "Failed to display image.");
+                self.show_failed(cx, e.to_string());
                 Err(e)
             }
         }
     }
 
-    /// Returns whether this `TextOrImage` is currently displaying an image or text.
+    /// Requests that this `TextOrImage` display `image_data`, resized to a thumbnail
+    /// that fits this widget's current layout width (and `THUMBNAIL_MAX_HEIGHT`).
+    ///
+    /// The decode and resize happen on a background thread via the shared
+    /// [`media_thumbnail_cache`], so this call returns immediately; the image appears
+    /// once the corresponding [`MediaThumbnailCacheAction::Loaded`] action arrives.
+    pub fn show_image_from_data(&mut self, cx: &mut Cx, original_mxc_uri: &OwnedMxcUri, image_data: Arc<[u8]>) {
+        let width = self.view.area().rect(cx).size.x.max(1.0) as usize;
+        let content_hash = media_thumbnail_cache::content_hash(&image_data);
+        self.pending_thumbnail_request = Some((content_hash, width, THUMBNAIL_MAX_HEIGHT));
+        self.image_value = Some(ImageValue {
+            original_mxc_uri: original_mxc_uri.clone(),
+            timeline_image_data: image_data.clone(),
+        });
+        media_thumbnail_cache::request_thumbnail(image_data, width, THUMBNAIL_MAX_HEIGHT);
+    }
+
+    /// Returns whether this `TextOrImage` is currently loading, displaying an image, or
+    /// displaying a failure message.
     fn status(&self) -> TextOrImageStatus {
-        self.status
+        self.status.clone()
     }
 }
 
@@ -156,8 +339,15 @@ impl TextOrImageRef {
         }
     }
 
+    /// See [TextOrImage::show_failed()].
+    pub fn show_failed<T: AsRef<str>>(&self, cx: &mut Cx, reason: T) {
+        if let Some(mut inner) = self.borrow_mut() {
+            inner.show_failed(cx, reason);
+        }
+    }
+
     /// See [TextOrImage::show_image()].
-    pub fn show_image<F, E>(&self, cx: &mut Cx, image_set_function: F) -> Result<(), E>
+    pub fn show_image<F, E: ToString>(&self, cx: &mut Cx, image_set_function: F) -> Result<(), E>
         where F: FnOnce(&mut Cx, ImageRef) -> Result<(usize, usize), E>
     {
         if let Some(mut inner) = self.borrow_mut() {
@@ -167,12 +357,19 @@ impl TextOrImageRef {
         }
     }
 
+    /// See [TextOrImage::show_image_from_data()].
+    pub fn show_image_from_data(&self, cx: &mut Cx, original_mxc_uri: &OwnedMxcUri, image_data: Arc<[u8]>) {
+        if let Some(mut inner) = self.borrow_mut() {
+            inner.show_image_from_data(cx, original_mxc_uri, image_data);
+        }
+    }
+
     /// See [TextOrImage::status()].
     pub fn status(&self) -> TextOrImageStatus {
         if let Some(inner) = self.borrow() {
             inner.status()
         } else {
-            TextOrImageStatus::Text
+            TextOrImageStatus::Loading
         }
     }
 
@@ -182,10 +379,12 @@ impl TextOrImageRef {
     }
 }
 
-/// Whether a `TextOrImage` instance is currently displaying text or an image.
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
+/// Whether a `TextOrImage` instance is currently loading, displaying an image, or
+/// displaying a failure message (with the failure reason).
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum TextOrImageStatus {
     #[default]
-    Text,
+    Loading,
     Image,
+    Failed(String),
 }